@@ -1,7 +1,7 @@
 extern crate gluster;
 extern crate tempdir;
 
-use backend::Backend;
+use backend::{Backend, get_busy_partitions};
 
 use std::io::Result;
 use std::path::Path;
@@ -51,6 +51,16 @@ impl Backend for GlusterBackend {
     /// Take any actions needed with this call to figure out if a disk is safe
     /// to remove from the cluster.
     fn safe_to_remove(&self, device: &Path, simulate: bool) -> Result<bool> {
+        if simulate {
+            return Ok(true);
+        }
+
+        let busy = get_busy_partitions(device)?;
+        if !busy.is_empty() {
+            debug!("{:?} has busy partitions, not safe to remove: {:?}", device, busy);
+            return Ok(false);
+        }
+
         Ok(true)
     }
 }
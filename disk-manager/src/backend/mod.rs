@@ -0,0 +1,171 @@
+mod ceph;
+mod gluster;
+mod zfs;
+
+use std::fs::read_dir;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+pub use self::ceph::CephBackend;
+pub use self::gluster::GlusterBackend;
+pub use self::zfs::ZfsBackend;
+
+pub trait Backend {
+    /// Add a disk to the cluster
+    /// If simulate is passed no action should be taken
+    fn add_disk(
+        &self,
+        device: &Path,
+        id: Option<u64>,
+        journal: Option<&str>,
+        journal_partition: Option<u32>,
+        simulate: bool,
+    ) -> Result<()>;
+
+    /// Remove a disk from a cluster
+    /// If simulate is passed no action should be taken
+    fn remove_disk(&self, device: &Path, simulate: bool) -> Result<()>;
+
+    /// Check if it's safe to remove a disk from a cluster
+    /// If simulate is passed then this always returns true
+    /// Take any actions needed with this call to figure out if a disk is safe
+    /// to remove from the cluster.
+    fn safe_to_remove(&self, device: &Path, simulate: bool) -> Result<bool>;
+}
+
+#[derive(Clone, Copy)]
+pub enum BackendType {
+    Ceph,
+    Gluster,
+    Zfs,
+}
+
+impl FromStr for BackendType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ceph" => Ok(BackendType::Ceph),
+            "gluster" => Ok(BackendType::Gluster),
+            "zfs" => Ok(BackendType::Zfs),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown backend type: {}", s),
+            )),
+        }
+    }
+}
+
+pub fn load_backend(backend: &BackendType, config_dir: Option<&Path>) -> Result<Box<Backend>> {
+    match *backend {
+        BackendType::Ceph => Ok(Box::new(CephBackend::new(config_dir)?)),
+        BackendType::Gluster => Ok(Box::new(GlusterBackend {})),
+        BackendType::Zfs => Ok(Box::new(ZfsBackend::new(config_dir)?)),
+    }
+}
+
+/// A partition that is currently in use and therefore unsafe to wipe.
+#[derive(Debug)]
+pub struct BusyPartition {
+    pub partition: String,
+    pub reason: String,
+}
+
+/// Find all partitions of `device` that are currently busy: mounted, active
+/// swap, or held open by a device-mapper/MD/dm-crypt mapping.  Usable by any
+/// backend's `safe_to_remove` implementation.
+pub fn get_busy_partitions(device: &Path) -> Result<Vec<BusyPartition>> {
+    let dev_name = device
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid device path"))?;
+
+    let mountinfo = read_file("/proc/self/mountinfo").unwrap_or_default();
+    let swaps = read_file("/proc/swaps").unwrap_or_default();
+
+    let mut busy = Vec::new();
+    for partition in partitions_of(dev_name)? {
+        if let Some(reason) = is_mounted(&mountinfo, &partition) {
+            busy.push(BusyPartition { partition: partition.clone(), reason });
+        } else if is_active_swap(&swaps, &partition) {
+            busy.push(BusyPartition {
+                partition: partition.clone(),
+                reason: "active swap device".to_string(),
+            });
+        } else if has_holders(&partition) {
+            busy.push(BusyPartition {
+                partition,
+                reason: "open device-mapper/MD holder".to_string(),
+            });
+        }
+    }
+
+    Ok(busy)
+}
+
+fn partitions_of(dev_name: &str) -> Result<Vec<String>> {
+    let mut partitions = Vec::new();
+    let sys_block = format!("/sys/block/{}", dev_name);
+    for entry in read_dir(&sys_block)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().unwrap_or_default();
+        if name.starts_with(dev_name) {
+            partitions.push(name);
+        }
+    }
+    if partitions.is_empty() {
+        partitions.push(dev_name.to_string());
+    }
+    Ok(partitions)
+}
+
+fn is_mounted(mountinfo: &str, partition: &str) -> Option<String> {
+    // mountinfo fields: ... (5) mount point ... "-" (separator) (fstype) (mount source) ...
+    // Only the mount-source field identifies the underlying device; matching
+    // any whitespace-separated field (eg the mount point) risks false positives.
+    let expected_source = format!("/dev/{}", partition);
+    for line in mountinfo.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let separator = match fields.iter().position(|&f| f == "-") {
+            Some(i) => i,
+            None => continue,
+        };
+        let mount_source = match fields.get(separator + 2) {
+            Some(s) => *s,
+            None => continue,
+        };
+        if mount_source == expected_source {
+            if let Some(mount_point) = fields.get(4) {
+                return Some(format!("mounted at {}", mount_point));
+            }
+        }
+    }
+    None
+}
+
+fn is_active_swap(swaps: &str, partition: &str) -> bool {
+    swaps
+        .lines()
+        .skip(1)
+        .any(|line| line.split_whitespace().next().map_or(false, |dev| {
+            dev.ends_with(partition)
+        }))
+}
+
+fn has_holders(partition: &str) -> bool {
+    // /sys/block only has entries for whole disks; partitions (and their
+    // holders dir) live under /sys/class/block.
+    let holders_dir = format!("/sys/class/block/{}/holders", partition);
+    read_dir(&holders_dir)
+        .map(|mut d| d.next().is_some())
+        .unwrap_or(false)
+}
+
+fn read_file(path: &str) -> Result<String> {
+    use std::fs::File;
+    use std::io::Read;
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+    Ok(contents)
+}
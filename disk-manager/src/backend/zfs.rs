@@ -0,0 +1,225 @@
+use backend::Backend;
+
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Deserialize)]
+struct ZfsConfig {
+    pool: String,
+}
+
+pub struct ZfsBackend {
+    pool: String,
+}
+
+impl ZfsBackend {
+    pub fn new(config_dir: Option<&Path>) -> Result<ZfsBackend> {
+        let config_dir = config_dir.ok_or_else(|| {
+            Error::new(ErrorKind::Other, "ZfsBackend requires a config directory")
+        })?;
+        let f = File::open(config_dir.join("zfs.json"))?;
+        let config: ZfsConfig = serde_json::from_reader(f).map_err(
+            |e| Error::new(ErrorKind::Other, e),
+        )?;
+        Ok(ZfsBackend { pool: config.pool })
+    }
+}
+
+/// A top-level vdev in a pool, along with the leaf devices underneath it (if
+/// any).  A bare top-level entry with no child devices is itself a
+/// single-disk vdev with no redundancy.
+struct Vdev {
+    name: String,
+    devices: Vec<(String, String)>,
+}
+
+/// `zpool status` prints short leaf/vdev names (eg `sda`), while callers
+/// pass full device paths (eg `/dev/sda`); compare basenames so the two
+/// line up regardless of which form either side uses.
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn same_device(a: &str, b: &str) -> bool {
+    basename(a) == basename(b)
+}
+
+/// The redundancy scheme of a top-level vdev, parsed from its `zpool status`
+/// name (`mirror-N`, `raidzN-N`/`raidz-N`, or a bare disk with no grouping).
+enum VdevKind {
+    Mirror,
+    RaidZ(u32),
+    Other,
+}
+
+fn vdev_kind(name: &str) -> VdevKind {
+    if name.starts_with("mirror") {
+        VdevKind::Mirror
+    } else if name.starts_with("raidz") {
+        let digits: String = name["raidz".len()..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        VdevKind::RaidZ(digits.parse().unwrap_or(1))
+    } else {
+        VdevKind::Other
+    }
+}
+
+/// Does `status` mention `device` anywhere in its vdev tree (as a top-level
+/// bare vdev or as a leaf member)?
+fn status_has_device(status: &str, device: &str) -> bool {
+    parse_zpool_status(status).iter().any(|vdev| {
+        same_device(&vdev.name, device) ||
+            vdev.devices.iter().any(|&(ref name, _)| same_device(name, device))
+    })
+}
+
+fn run(cmd: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(cmd).args(args).output()?;
+    if !output.status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Parse the indented device tree out of `zpool status -p <pool>` output,
+/// grouping each top-level vdev (mirror-N, raidzN-N, or a bare disk) with its
+/// child devices.
+fn parse_zpool_status(status: &str) -> Vec<Vdev> {
+    let mut lines = status.lines().skip_while(
+        |l| !l.trim_start().starts_with("NAME"),
+    );
+    lines.next(); // consume the "NAME STATE READ WRITE CKSUM" header
+    lines.next(); // consume the pool's own top-of-tree line
+
+    let mut vdevs: Vec<Vdev> = Vec::new();
+    let mut baseline_indent: Option<usize> = None;
+
+    for line in lines {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            break;
+        }
+        let indent = line.len() - trimmed.len();
+        let mut fields = trimmed.split_whitespace();
+        let name = match fields.next() {
+            Some(n) => n.to_string(),
+            None => continue,
+        };
+        let state = fields.next().unwrap_or("").to_string();
+
+        let baseline = *baseline_indent.get_or_insert(indent);
+        if indent == baseline {
+            vdevs.push(Vdev { name, devices: Vec::new() });
+        } else if let Some(vdev) = vdevs.last_mut() {
+            vdev.devices.push((name, state));
+        }
+    }
+    vdevs
+}
+
+/// Return false when removing `device` would leave its containing vdev with
+/// no remaining healthy redundancy.  What "remaining redundancy" means
+/// depends on the vdev's type, not a single fixed survivor count:
+///   - a bare, unmirrored vdev has none to begin with.
+///   - a mirror needs at least two other members still ONLINE afterwards -
+///     one surviving copy is not redundancy, it's the last copy.
+///   - a raidzN can tolerate N member failures total; if removing this
+///     device would push the number of non-ONLINE members past N, the
+///     parity budget is already spent.
+fn vdev_has_redundancy_without(status: &str, device: &str) -> bool {
+    let vdevs = parse_zpool_status(status);
+    for vdev in &vdevs {
+        if same_device(&vdev.name, device) && vdev.devices.is_empty() {
+            return false;
+        }
+
+        if !vdev.devices.iter().any(|&(ref name, _)| same_device(name, device)) {
+            continue;
+        }
+
+        let already_faulted = vdev.devices
+            .iter()
+            .filter(|&&(ref name, ref state)| {
+                !same_device(name, device) && state != "ONLINE"
+            })
+            .count() as u32;
+
+        return match vdev_kind(&vdev.name) {
+            VdevKind::Mirror => {
+                let healthy_survivors = vdev.devices
+                    .iter()
+                    .filter(|&&(ref name, ref state)| {
+                        !same_device(name, device) && state == "ONLINE"
+                    })
+                    .count();
+                healthy_survivors >= 2
+            }
+            VdevKind::RaidZ(parity) => already_faulted + 1 <= parity,
+            VdevKind::Other => false,
+        };
+    }
+    // Not part of this pool's config tree; nothing here to protect.
+    true
+}
+
+impl Backend for ZfsBackend {
+    /// Attach the device to the configured pool, or `zpool replace` it in
+    /// place if `device` already appears anywhere in `zpool status` (its
+    /// state is not checked).
+    fn add_disk(
+        &self,
+        device: &Path,
+        _id: Option<u64>,
+        _journal: Option<&str>,
+        _journal_partition: Option<u32>,
+        simulate: bool,
+    ) -> Result<()> {
+        if simulate {
+            return Ok(());
+        }
+        let dev = device.to_string_lossy();
+        let status = Command::new("zpool")
+            .args(&["status", "-p", &self.pool])
+            .output()?;
+        let status_text = String::from_utf8_lossy(&status.stdout);
+
+        if status_has_device(&status_text, &dev) {
+            run("zpool", &["replace", &self.pool, &dev])
+        } else {
+            run("zpool", &["add", &self.pool, &dev])
+        }
+    }
+
+    /// Offline and detach the vdev member, leaving the actual wipe to the caller.
+    fn remove_disk(&self, device: &Path, simulate: bool) -> Result<()> {
+        if simulate {
+            return Ok(());
+        }
+        let dev = device.to_string_lossy();
+        run("zpool", &["offline", &self.pool, &dev])?;
+        run("zpool", &["detach", &self.pool, &dev])
+    }
+
+    /// Check if it's safe to remove a disk from a cluster
+    /// If simulate is passed then this always returns true
+    /// Take any actions needed with this call to figure out if a disk is safe
+    /// to remove from the cluster.
+    fn safe_to_remove(&self, device: &Path, simulate: bool) -> Result<bool> {
+        if simulate {
+            return Ok(true);
+        }
+        let dev = device.to_string_lossy();
+        let output = Command::new("zpool")
+            .args(&["status", "-p", &self.pool])
+            .output()?;
+        let status_text = String::from_utf8_lossy(&output.stdout);
+        Ok(vdev_has_redundancy_without(&status_text, &dev))
+    }
+}
@@ -5,6 +5,7 @@ extern crate bytes;
 extern crate clap;
 extern crate gpt;
 extern crate hashicorp_vault;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate protobuf;
@@ -13,18 +14,23 @@ extern crate serde_derive;
 extern crate serde;
 extern crate serde_json;
 extern crate simplelog;
+extern crate uuid;
 extern crate zmq;
 
 mod backend;
 
-use std::io::{Error, ErrorKind, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Result, Seek, SeekFrom, Read as IoRead, Write as IoWrite};
+use std::mem;
+use std::os::unix::io::AsRawFd;
 use std::path::Path;
+use std::process::Command;
 use std::str::FromStr;
 use std::thread;
 use std::time::Duration;
 
 use api::service::{Disk, Disks, DiskType, Op, OpBoolResult, ResultType, OpResult, Partition,
-                   PartitionInfo};
+                   PartitionInfo, SmartAttribute, SmartResult};
 use backend::BackendType;
 use block_utils::{Device, MediaType};
 use clap::{Arg, App};
@@ -34,10 +40,58 @@ use hashicorp_vault::client::VaultClient;
 use protobuf::Message as ProtobufMsg;
 use protobuf::RepeatedField;
 use protobuf::core::parse_from_bytes;
+use serde_json::Value;
 use simplelog::{Config, SimpleLogger};
+use uuid::Uuid;
 use zmq::{Message, Socket};
 use zmq::Result as ZmqResult;
 
+// SMART attribute ids whose raw value indicates a drive is on its way out.
+// 5   - Reallocated_Sector_Ct
+// 187 - Reported_Uncorrect
+// 197 - Current_Pending_Sector
+// 198 - Offline_Uncorrectable
+const CRITICAL_SMART_ATTRIBUTES: &[i64] = &[5, 187, 197, 198];
+const SECTOR_SIZE: u64 = 512;
+// 2048 sectors == 1 MiB, the alignment parted/gdisk use for new partitions.
+const PARTITION_ALIGNMENT: u64 = 2048;
+const GPT_ENTRY_SIZE: u64 = 128;
+// Bytes 0..92 of the GPT header are the defined fields the CRC32 covers; the
+// rest of the sector is reserved and must be zero.
+const GPT_HEADER_SIZE: u32 = 92;
+
+// NVME_IOCTL_ADMIN_CMD == _IOWR('N', 0x41, struct nvme_admin_cmd), per <linux/nvme_ioctl.h>
+const NVME_IOCTL_ADMIN_CMD: libc::c_ulong = 0xC048_4E41;
+const NVME_ADMIN_OPCODE_IDENTIFY: u8 = 0x06;
+const NVME_ADMIN_OPCODE_GET_LOG_PAGE: u8 = 0x02;
+const NVME_IDENTIFY_CNS_CONTROLLER: u32 = 1;
+const NVME_LOG_PAGE_SMART_HEALTH: u32 = 0x02;
+const NVME_IDENTIFY_DATA_LEN: usize = 4096;
+const NVME_SMART_LOG_LEN: usize = 512;
+
+// Mirrors the kernel's struct nvme_admin_cmd used for NVMe admin passthrough ioctls.
+#[repr(C)]
+struct NvmeAdminCmd {
+    opcode: u8,
+    flags: u8,
+    rsvd1: u16,
+    nsid: u32,
+    cdw2: u32,
+    cdw3: u32,
+    metadata: u64,
+    addr: u64,
+    metadata_len: u32,
+    data_len: u32,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
+    timeout_ms: u32,
+    result: u32,
+}
+
 fn convert_media_to_disk_type(m: MediaType) -> DiskType {
     match m {
         MediaType::Loopback => DiskType::LOOPBACK,
@@ -54,6 +108,12 @@ fn convert_media_to_disk_type(m: MediaType) -> DiskType {
 
 /*
  Server that manages disks
+
+ A single blocking REP socket would stall every client behind whichever
+ request is currently running (eg a multi-minute add_disk).  Instead the
+ front-end ROUTER fans requests out over an inproc DEALER to a fixed pool of
+ worker threads, each running its own REP-style loop, so independent clients
+ are serviced concurrently.
  */
 fn listen(
     backend_type: backend::BackendType,
@@ -61,16 +121,60 @@ fn listen(
     vault_endpoint: &str,
     vault_token: &str,
     vault_key: &str,
+    workers: usize,
 ) -> ZmqResult<()> {
     debug!("Starting zmq listener with version({:?})", zmq::version());
     let context = zmq::Context::new();
-    let mut responder = context.socket(zmq::REP)?;
+    let mut frontend = context.socket(zmq::ROUTER)?;
+    let mut backend_sock = context.socket(zmq::DEALER)?;
+
+    frontend.bind("tcp://*:5555")?;
+    backend_sock.bind("inproc://workers")?;
+
+    for id in 0..workers {
+        let worker_context = context.clone();
+        let config_dir = config_dir.to_path_buf();
+        let vault_endpoint = vault_endpoint.to_string();
+        let vault_token = vault_token.to_string();
+        let vault_key = vault_key.to_string();
+
+        thread::spawn(move || {
+            if let Err(e) = worker_loop(
+                id,
+                worker_context,
+                backend_type,
+                &config_dir,
+                &vault_endpoint,
+                &vault_token,
+                &vault_key,
+            )
+            {
+                error!("Worker {} exited with error: {:?}", id, e);
+            }
+        });
+    }
+
+    zmq::proxy(&mut frontend, &mut backend_sock)
+}
 
-    assert!(responder.bind("tcp://*:5555").is_ok());
+/// One REP-style worker servicing requests handed to it by the ROUTER/DEALER
+/// proxy over `inproc://workers`.
+fn worker_loop(
+    id: usize,
+    context: zmq::Context,
+    backend_type: backend::BackendType,
+    config_dir: &Path,
+    vault_endpoint: &str,
+    vault_token: &str,
+    vault_key: &str,
+) -> ZmqResult<()> {
+    let mut responder = context.socket(zmq::REP)?;
+    responder.connect("inproc://workers")?;
+    debug!("Worker {} ready", id);
 
     loop {
         let msg = responder.recv_bytes(0)?;
-        debug!("Got msg len: {}", msg.len());
+        debug!("Worker {} got msg len: {}", id, msg.len());
         trace!("Parsing msg {:?} as hex", msg);
         let operation = match parse_from_bytes::<api::service::Operation>(&msg) {
             Ok(bytes) => bytes,
@@ -92,99 +196,145 @@ fn listen(
             continue;
         }
         debug!("Operation requested: {:?}", operation.get_Op_type());
-        match operation.get_Op_type() {
-            Op::Add => {
-                let id = if operation.has_osd_id() {
-                    Some(operation.get_osd_id())
-                } else {
-                    None
-                };
-                let journal = if operation.has_osd_journal() {
-                    Some(operation.get_osd_journal())
-                } else {
-                    None
-                };
-                let journal_partition = if operation.has_osd_journal_partition() {
-                    Some(operation.get_osd_journal_partition())
-                } else {
-                    None
-                };
-                if !operation.has_disk() {
-                    error!("Add operation must include disk field.  Ignoring request");
-                    continue;
-                }
-                match add_disk(
-                    &mut responder,
-                    operation.get_disk(),
-                    &backend_type,
-                    id,
-                    journal,
-                    journal_partition,
-                    config_dir,
-                ) {
-                    Ok(_) => {
-                        info!("Add disk successful");
-                    }
-                    Err(e) => {
-                        error!("Add disk error: {:?}", e);
-                    }
-                };
+        handle_operation(&mut responder, &operation, &backend_type, config_dir);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn handle_operation(
+    responder: &mut Socket,
+    operation: &api::service::Operation,
+    backend_type: &backend::BackendType,
+    config_dir: &Path,
+) {
+    match operation.get_Op_type() {
+        Op::Add => {
+            let id = if operation.has_osd_id() {
+                Some(operation.get_osd_id())
+            } else {
+                None
+            };
+            let journal = if operation.has_osd_journal() {
+                Some(operation.get_osd_journal())
+            } else {
+                None
+            };
+            let journal_partition = if operation.has_osd_journal_partition() {
+                Some(operation.get_osd_journal_partition())
+            } else {
+                None
+            };
+            if !operation.has_disk() {
+                error!("Add operation must include disk field.  Ignoring request");
+                return;
             }
-            Op::AddPartition => {
-                //
+            match add_disk(
+                responder,
+                operation.get_disk(),
+                backend_type,
+                id,
+                journal,
+                journal_partition,
+                config_dir,
+            ) {
+                Ok(_) => {
+                    info!("Add disk successful");
+                }
+                Err(e) => {
+                    error!("Add disk error: {:?}", e);
+                }
+            };
+        }
+        Op::AddPartition => {
+            if !operation.has_disk() {
+                error!("AddPartition operation must include disk field.  Ignoring request");
+                return;
             }
-            Op::List => {
-                match list_disks(&mut responder) {
-                    Ok(_) => {
-                        info!("List disks successful");
-                    }
-                    Err(e) => {
-                        error!("List disks error: {:?}", e);
-                    }
-                };
+            let start_lba = if operation.has_start_lba() {
+                Some(operation.get_start_lba())
+            } else {
+                None
+            };
+            match add_partition(
+                responder,
+                operation.get_disk(),
+                start_lba,
+                operation.get_size(),
+                operation.get_name(),
+                operation.get_type_guid(),
+                config_dir,
+            ) {
+                Ok(_) => {
+                    info!("Add partition successful");
+                }
+                Err(e) => {
+                    error!("Add partition error: {:?}", e);
+                }
+            };
+        }
+        Op::GetSmartData => {
+            if !operation.has_disk() {
+                error!("GetSmartData operation must include disk field.  Ignoring request");
+                return;
             }
-            Op::Remove => {
-                if !operation.has_disk() {
-                    error!("Remove operation must include disk field.  Ignoring request");
-                    continue;
+            match get_smart_data(responder, operation.get_disk()) {
+                Ok(_) => {
+                    info!("Get smart data successful");
+                }
+                Err(e) => {
+                    error!("Get smart data error: {:?}", e);
+                }
+            };
+        }
+        Op::List => {
+            match list_disks(responder) {
+                Ok(_) => {
+                    info!("List disks successful");
                 }
-                match remove_disk(
-                    &mut responder,
-                    operation.get_disk(),
-                    &backend_type,
-                    config_dir,
-                ) {
-                    Ok(_) => {
-                        info!("Remove disk successful");
-                    }
-                    Err(e) => {
-                        error!("Remove disk error: {:?}", e);
-                    }
-                };
+                Err(e) => {
+                    error!("List disks error: {:?}", e);
+                }
+            };
+        }
+        Op::Remove => {
+            if !operation.has_disk() {
+                error!("Remove operation must include disk field.  Ignoring request");
+                return;
             }
-            Op::SafeToRemove => {
-                if !operation.has_disk() {
-                    error!("SafeToRemove operation must include disk field.  Ignoring request");
-                    continue;
+            match remove_disk(
+                responder,
+                operation.get_disk(),
+                backend_type,
+                config_dir,
+            ) {
+                Ok(_) => {
+                    info!("Remove disk successful");
                 }
-                match safe_to_remove_disk(
-                    &mut responder,
-                    operation.get_disk(),
-                    &backend_type,
-                    config_dir,
-                ) {
-                    Ok(_) => {
-                        info!("Remove disk successful");
-                    }
-                    Err(e) => {
-                        error!("Remove disk error: {:?}", e);
-                    }
-
-                };
+                Err(e) => {
+                    error!("Remove disk error: {:?}", e);
+                }
+            };
+        }
+        Op::SafeToRemove => {
+            if !operation.has_disk() {
+                error!("SafeToRemove operation must include disk field.  Ignoring request");
+                return;
             }
-        };
-        thread::sleep(Duration::from_millis(10));
-    }
+            match safe_to_remove_disk(
+                responder,
+                operation.get_disk(),
+                backend_type,
+                config_dir,
+            ) {
+                Ok(_) => {
+                    info!("Remove disk successful");
+                }
+                Err(e) => {
+                    error!("Remove disk error: {:?}", e);
+                }
+            };
+        }
+    };
 }
 
 fn add_disk(
@@ -223,6 +373,368 @@ fn add_disk(
     Ok(())
 }
 
+/// Shell out to smartctl and parse its JSON output into a SmartResult.
+/// Returns an error if smartctl can't be run or its output isn't JSON; callers
+/// then fall back to an empty, non-failing result.  A device without SMART
+/// support (eg virtual/loopback devices) yields a non-failing result with no
+/// attributes.
+fn get_smart_health(dev_path: &str) -> Result<SmartResult> {
+    let output = Command::new("smartctl")
+        .args(&["-A", "-H", "-j", dev_path])
+        .output()?;
+
+    let json: Value = serde_json::from_slice(&output.stdout).map_err(|e| {
+        Error::new(ErrorKind::Other, e)
+    })?;
+
+    let mut result = SmartResult::new();
+    let mut predict_fail = !json["smart_status"]["passed"].as_bool().unwrap_or(true);
+
+    if let Some(table) = json["ata_smart_attributes"]["table"].as_array() {
+        let mut attributes = Vec::with_capacity(table.len());
+        for attr in table {
+            let id = attr["id"].as_i64().unwrap_or(0);
+            let raw = attr["raw"]["value"].as_i64().unwrap_or(0);
+
+            if CRITICAL_SMART_ATTRIBUTES.contains(&id) && raw > 0 {
+                predict_fail = true;
+            }
+
+            let mut a = SmartAttribute::new();
+            a.set_id(id as u32);
+            a.set_name(attr["name"].as_str().unwrap_or("").to_string());
+            a.set_raw_value(raw);
+            a.set_normalized_value(attr["value"].as_i64().unwrap_or(0) as u32);
+            a.set_threshold(attr["thresh"].as_i64().unwrap_or(0) as u32);
+            attributes.push(a);
+        }
+        result.set_attribute(RepeatedField::from_vec(attributes));
+    }
+    result.set_predict_fail(predict_fail);
+
+    Ok(result)
+}
+
+fn get_smart_data(s: &mut Socket, d: &str) -> Result<()> {
+    let health = get_smart_health(d).unwrap_or_else(|e| {
+        debug!("Unable to gather smart data for {}: {:?}", d, e);
+        SmartResult::new()
+    });
+    let encoded = health.write_to_bytes().map_err(
+        |e| Error::new(ErrorKind::Other, e),
+    )?;
+    let msg = Message::from_slice(&encoded)?;
+    debug!("Responding to client with msg len: {}", msg.len());
+    s.send_msg(msg, 0)?;
+    Ok(())
+}
+
+fn add_partition(
+    s: &mut Socket,
+    device: &str,
+    start_lba: Option<u64>,
+    size: u64,
+    name: &str,
+    type_guid: &str,
+    _config_dir: &Path,
+) -> Result<()> {
+    let mut result = OpResult::new();
+    match create_gpt_partition(device, start_lba, size, name, type_guid) {
+        Ok(part_guid) => {
+            result.set_result(ResultType::OK);
+            result.set_partition_id(part_guid.hyphenated().to_string());
+        }
+        Err(e) => {
+            result.set_result(ResultType::ERR);
+            result.set_error_msg(e.to_string());
+        }
+    }
+    let encoded = result.write_to_bytes().map_err(
+        |e| Error::new(ErrorKind::Other, e),
+    )?;
+    let msg = Message::from_slice(&encoded)?;
+    debug!("Responding to client with msg len: {}", msg.len());
+    s.send_msg(msg, 0)?;
+    Ok(())
+}
+
+/// Allocate a free slot in the GPT partition array, write the new entry and
+/// rewrite both the primary (LBA 1) and backup GPT headers with recalculated
+/// CRC32 checksums.  Returns the new partition's GUID on success.
+fn create_gpt_partition(
+    device: &str,
+    start_lba: Option<u64>,
+    size: u64,
+    name: &str,
+    type_guid: &str,
+) -> Result<Uuid> {
+    let type_guid = Uuid::parse_str(type_guid).map_err(
+        |e| Error::new(ErrorKind::InvalidInput, e.to_string()),
+    )?;
+    let header = read_header(device)?;
+    let existing = read_partitions(device, &header)?;
+
+    let requested_sectors = (size + SECTOR_SIZE - 1) / SECTOR_SIZE;
+    let first_lba = match start_lba {
+        Some(lba) => align_up(lba, PARTITION_ALIGNMENT),
+        None => {
+            let last_used = existing.iter().map(|p| p.last_LBA).max().unwrap_or(
+                header.first_usable,
+            );
+            align_up(last_used + 1, PARTITION_ALIGNMENT)
+        }
+    };
+    let last_lba = first_lba + requested_sectors - 1;
+
+    if last_lba > header.last_usable {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Requested partition does not fit before the backup GPT header",
+        ));
+    }
+    if existing.iter().any(|p| {
+        first_lba <= p.last_LBA && last_lba >= p.first_LBA
+    })
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Requested partition range overlaps an existing partition",
+        ));
+    }
+
+    let slot = find_free_slot(device, &header)?;
+
+    let partition_guid = Uuid::new_v4();
+    let mut dev = OpenOptions::new().read(true).write(true).open(device)?;
+
+    write_partition_entry(
+        &mut dev,
+        header.part_start,
+        slot,
+        &partition_guid,
+        &type_guid,
+        first_lba,
+        last_lba,
+        name,
+    )?;
+    rewrite_gpt_headers(&mut dev, &header)?;
+
+    // Ask the kernel to re-read the partition table so the new device node shows up.
+    let _ = Command::new("partprobe").arg(device).status();
+    let _ = read_partitions(device, &read_header(device)?)?;
+
+    Ok(partition_guid)
+}
+
+fn align_up(lba: u64, alignment: u64) -> u64 {
+    ((lba + alignment - 1) / alignment) * alignment
+}
+
+/// The GPT spec stores GUIDs mixed-endian: the first three fields
+/// (time-low, time-mid, time-hi-and-version) are little-endian, while the
+/// last two (clock-seq and node) keep RFC 4122's big-endian byte order.
+/// `Uuid::as_bytes()` returns the straight RFC order, so every GUID written
+/// to an on-disk structure needs this transform first.
+fn guid_to_mixed_endian(uuid: &Uuid) -> [u8; 16] {
+    let b = uuid.as_bytes();
+    let mut out = [0u8; 16];
+    out[0..4].copy_from_slice(&[b[3], b[2], b[1], b[0]]);
+    out[4..6].copy_from_slice(&[b[5], b[4]]);
+    out[6..8].copy_from_slice(&[b[7], b[6]]);
+    out[8..16].copy_from_slice(&b[8..16]);
+    out
+}
+
+/// Scan the on-disk partition array for the first entry whose type GUID is
+/// all-zero (ie genuinely unused), rather than assuming entries are packed
+/// contiguously from index 0.
+fn find_free_slot(device: &str, header: &gpt::header::Header) -> Result<u64> {
+    let mut dev = OpenOptions::new().read(true).open(device)?;
+    dev.seek(SeekFrom::Start(header.part_start * SECTOR_SIZE))?;
+
+    let mut entry = vec![0u8; GPT_ENTRY_SIZE as usize];
+    for slot in 0..header.num_parts as u64 {
+        dev.read_exact(&mut entry)?;
+        if entry[0..16].iter().all(|&b| b == 0) {
+            return Ok(slot);
+        }
+    }
+
+    Err(Error::new(ErrorKind::Other, "No free partition slots"))
+}
+
+fn write_partition_entry(
+    dev: &mut ::std::fs::File,
+    part_start_lba: u64,
+    slot: u64,
+    part_guid: &Uuid,
+    type_guid: &Uuid,
+    first_lba: u64,
+    last_lba: u64,
+    name: &str,
+) -> Result<()> {
+    let mut entry = vec![0u8; GPT_ENTRY_SIZE as usize];
+    entry[0..16].copy_from_slice(&guid_to_mixed_endian(type_guid));
+    entry[16..32].copy_from_slice(&guid_to_mixed_endian(part_guid));
+    entry[32..40].copy_from_slice(&first_lba.to_le_bytes());
+    entry[40..48].copy_from_slice(&last_lba.to_le_bytes());
+    let utf16_name: Vec<u16> = name.encode_utf16().collect();
+    for (i, code_unit) in utf16_name.iter().take(36).enumerate() {
+        let offset = 56 + i * 2;
+        entry[offset..offset + 2].copy_from_slice(&code_unit.to_le_bytes());
+    }
+
+    let offset = part_start_lba * SECTOR_SIZE + slot * GPT_ENTRY_SIZE;
+    dev.seek(SeekFrom::Start(offset))?;
+    dev.write_all(&entry)?;
+    Ok(())
+}
+
+/// Recompute the partition array and header CRC32s and rewrite both the
+/// primary header at LBA 1 and the backup header at the last LBA.
+fn rewrite_gpt_headers(dev: &mut ::std::fs::File, header: &gpt::header::Header) -> Result<()> {
+    let array_size = (header.num_parts as u64) * GPT_ENTRY_SIZE;
+    let array_sectors = (array_size + SECTOR_SIZE - 1) / SECTOR_SIZE;
+
+    let mut array = vec![0u8; array_size as usize];
+    dev.seek(SeekFrom::Start(header.part_start * SECTOR_SIZE))?;
+    dev.read_exact(&mut array)?;
+    let parts_crc32 = crc32(&array);
+
+    let backup_part_start = header.backup_lba - array_sectors;
+    dev.seek(SeekFrom::Start(backup_part_start * SECTOR_SIZE))?;
+    dev.write_all(&array)?;
+
+    for &(this_lba, other_lba, part_start) in
+        &[
+            (header.current_lba, header.backup_lba, header.part_start),
+            (header.backup_lba, header.current_lba, backup_part_start),
+        ]
+    {
+        let mut buf = vec![0u8; SECTOR_SIZE as usize];
+        buf[0..8].copy_from_slice(b"EFI PART");
+        buf[8..12].copy_from_slice(&header.revision.to_le_bytes());
+        buf[12..16].copy_from_slice(&GPT_HEADER_SIZE.to_le_bytes());
+        // buf[16..20] (header crc32) is filled in last, after everything else.
+        buf[24..32].copy_from_slice(&this_lba.to_le_bytes());
+        buf[32..40].copy_from_slice(&other_lba.to_le_bytes());
+        buf[40..48].copy_from_slice(&header.first_usable.to_le_bytes());
+        buf[48..56].copy_from_slice(&header.last_usable.to_le_bytes());
+        buf[56..72].copy_from_slice(&guid_to_mixed_endian(&header.disk_guid));
+        buf[72..80].copy_from_slice(&part_start.to_le_bytes());
+        buf[80..84].copy_from_slice(&header.num_parts.to_le_bytes());
+        buf[84..88].copy_from_slice(&(GPT_ENTRY_SIZE as u32).to_le_bytes());
+        buf[88..92].copy_from_slice(&parts_crc32.to_le_bytes());
+
+        let header_crc = crc32(&buf[0..GPT_HEADER_SIZE as usize]);
+        buf[16..20].copy_from_slice(&header_crc.to_le_bytes());
+
+        dev.seek(SeekFrom::Start(this_lba * SECTOR_SIZE))?;
+        dev.write_all(&buf)?;
+    }
+
+    Ok(())
+}
+
+// Standard (IEEE 802.3) reflected CRC32, as used by the GPT on-disk checksums.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Controller and health-log detail gathered from an NVMe device via admin
+/// passthrough ioctls, used to populate the NVMe-specific `Disk` fields.
+struct NvmeInfo {
+    firmware_revision: String,
+    model_number: String,
+    num_namespaces: u32,
+    critical_warning: u32,
+    percentage_used: u32,
+    media_errors: u64,
+    unsafe_shutdowns: u64,
+}
+
+fn nvme_admin_passthrough(
+    dev: &File,
+    opcode: u8,
+    nsid: u32,
+    cdw10: u32,
+    buf: &mut [u8],
+) -> Result<()> {
+    let mut cmd: NvmeAdminCmd = unsafe { mem::zeroed() };
+    cmd.opcode = opcode;
+    cmd.nsid = nsid;
+    cmd.addr = buf.as_mut_ptr() as u64;
+    cmd.data_len = buf.len() as u32;
+    cmd.cdw10 = cdw10;
+
+    let ret = unsafe { libc::ioctl(dev.as_raw_fd(), NVME_IOCTL_ADMIN_CMD, &mut cmd) };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn trim_ascii(raw: &[u8]) -> String {
+    String::from_utf8_lossy(raw).trim().to_string()
+}
+
+fn get_nvme_health(dev_path: &str) -> Result<NvmeInfo> {
+    let dev = OpenOptions::new().read(true).write(true).open(dev_path)?;
+
+    let mut identify_controller = [0u8; NVME_IDENTIFY_DATA_LEN];
+    nvme_admin_passthrough(
+        &dev,
+        NVME_ADMIN_OPCODE_IDENTIFY,
+        0,
+        NVME_IDENTIFY_CNS_CONTROLLER,
+        &mut identify_controller,
+    )?;
+
+    let mut smart_log = [0u8; NVME_SMART_LOG_LEN];
+    // cdw10: bits 0-7 log page id, bits 16-31 (dwords - 1) in the returned data.
+    let log_cdw10 = (((NVME_SMART_LOG_LEN / 4 - 1) as u32) << 16) | NVME_LOG_PAGE_SMART_HEALTH;
+    nvme_admin_passthrough(
+        &dev,
+        NVME_ADMIN_OPCODE_GET_LOG_PAGE,
+        0xFFFF_FFFF,
+        log_cdw10,
+        &mut smart_log,
+    )?;
+
+    Ok(NvmeInfo {
+        model_number: trim_ascii(&identify_controller[24..64]),
+        firmware_revision: trim_ascii(&identify_controller[64..72]),
+        num_namespaces: u32::from_le_bytes([
+            identify_controller[516],
+            identify_controller[517],
+            identify_controller[518],
+            identify_controller[519],
+        ]),
+        critical_warning: smart_log[0] as u32,
+        percentage_used: smart_log[5] as u32,
+        // The spec defines these as 128-bit fields; realistic values fit in the low 64 bits.
+        media_errors: u64::from_le_bytes([
+            smart_log[160], smart_log[161], smart_log[162], smart_log[163],
+            smart_log[164], smart_log[165], smart_log[166], smart_log[167],
+        ]),
+        unsafe_shutdowns: u64::from_le_bytes([
+            smart_log[144], smart_log[145], smart_log[146], smart_log[147],
+            smart_log[148], smart_log[149], smart_log[150], smart_log[151],
+        ]),
+    })
+}
+
 fn get_disks() -> Result<Vec<Disk>> {
     let mut disks: Vec<Disk> = Vec::new();
     debug!("Searching for block devices");
@@ -246,9 +758,30 @@ fn get_disks() -> Result<Vec<Disk>> {
         // This will skip partition_info if it fails to gather.  Blank disks will fail
         let p = get_partition_info(&dev_path).unwrap_or(PartitionInfo::new());
         //Translate block_utils MediaType -> Protobuf DiskType
-        d.set_field_type(convert_media_to_disk_type(device.media_type));
-        d.set_dev_path(dev_path);
+        let disk_type = convert_media_to_disk_type(device.media_type);
+        d.set_field_type(disk_type);
+        d.set_dev_path(dev_path.clone());
         d.set_partitions(p);
+        d.set_health(get_smart_health(&dev_path).unwrap_or_else(|e| {
+            debug!("Unable to gather smart data for {}: {:?}", dev_path, e);
+            SmartResult::new()
+        }));
+        if disk_type == DiskType::NVME {
+            match get_nvme_health(&dev_path) {
+                Ok(info) => {
+                    d.set_firmware_revision(info.firmware_revision);
+                    d.set_model_number(info.model_number);
+                    d.set_num_namespaces(info.num_namespaces);
+                    d.set_critical_warning(info.critical_warning);
+                    d.set_percentage_used(info.percentage_used);
+                    d.set_media_errors(info.media_errors);
+                    d.set_unsafe_shutdowns(info.unsafe_shutdowns);
+                }
+                Err(e) => {
+                    debug!("Unable to gather nvme health for {}: {:?}", dev_path, e);
+                }
+            }
+        }
         if let Some(serial) = device.serial_number {
             d.set_serial_number(serial);
         }
@@ -384,8 +917,7 @@ fn main() {
                 .default_value("ceph")
                 .help("Backend cluster type to manage disks for")
                 .long("backend")
-                // TODO: Insert other backend values here as they become available
-                .possible_values(&["ceph"])
+                .possible_values(&["ceph", "zfs"])
                 .takes_value(true)
                 .required(false),
         )
@@ -397,6 +929,14 @@ fn main() {
                 .takes_value(true)
                 .required(false),
         )
+        .arg(
+            Arg::with_name("workers")
+                .default_value("5")
+                .help("Number of worker threads servicing requests concurrently")
+                .long("workers")
+                .takes_value(true)
+                .required(false),
+        )
         .arg(Arg::with_name("v").short("v").multiple(true).help(
             "Sets the level of verbosity",
         ))
@@ -408,8 +948,17 @@ fn main() {
     };
     let config_dir = Path::new(matches.value_of("configdir").unwrap());
     let backend = BackendType::from_str(matches.value_of("backend").unwrap()).unwrap();
+    let workers = value_t!(matches, "workers", usize).unwrap_or(5);
     let _ = SimpleLogger::init(level, Config::default());
-    match listen(backend, config_dir, "vault_endpoint", "vault_token", "key") {
+    match listen(
+        backend,
+        config_dir,
+        "vault_endpoint",
+        "vault_token",
+        "key",
+        workers,
+    )
+    {
         Ok(_) => {
             println!("Finished");
         }